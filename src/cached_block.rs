@@ -0,0 +1,141 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use ext4_rs::{BlockDevice, BLOCK_SIZE};
+
+/// Default number of blocks kept warm by [`CachedBlockDevice`] when the
+/// caller doesn't override it on the command line.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+struct CachedBlock {
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+struct CacheState {
+    blocks: HashMap<u64, CachedBlock>,
+    // Front = least recently used, back = most recently used.
+    order: VecDeque<u64>,
+}
+
+/// Write-back LRU cache that sits between `Ext4Fuse` and an inner
+/// [`BlockDevice`].
+///
+/// The inner device (e.g. [`Disk`](crate::Disk)) re-opens the backing image
+/// file on every access, so without this layer a single `read()`/`readdir()`
+/// turns into dozens of fresh `open()`+`seek()`+`read_exact()` syscalls.
+/// `CachedBlockDevice` keeps recently touched blocks in memory, serves hits
+/// without touching `inner`, and marks written blocks dirty instead of
+/// writing them through immediately. Dirty blocks are flushed back to
+/// `inner` when they're evicted, and [`flush`](Self::flush) can be called
+/// explicitly (e.g. on FUSE `destroy`/unmount) to push out everything still
+/// dirty.
+pub struct CachedBlockDevice<D: BlockDevice> {
+    inner: D,
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl<D: BlockDevice> CachedBlockDevice<D> {
+    pub fn new(inner: D, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity: capacity.max(1),
+            state: Mutex::new(CacheState {
+                blocks: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    fn touch(state: &mut CacheState, block_no: u64) {
+        if let Some(pos) = state.order.iter().position(|&b| b == block_no) {
+            state.order.remove(pos);
+        }
+        state.order.push_back(block_no);
+    }
+
+    fn evict_if_needed(&self, state: &mut CacheState) {
+        while state.order.len() > self.capacity {
+            let Some(victim) = state.order.pop_front() else {
+                break;
+            };
+            if let Some(block) = state.blocks.remove(&victim) {
+                if block.dirty {
+                    self.inner
+                        .write_offset(victim as usize * BLOCK_SIZE as usize, &block.data);
+                }
+            }
+        }
+    }
+
+    /// Write every dirty block back to `inner`. Called on unmount so no
+    /// buffered data is lost.
+    pub fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        for (&block_no, block) in state.blocks.iter_mut() {
+            if block.dirty {
+                self.inner
+                    .write_offset(block_no as usize * BLOCK_SIZE as usize, &block.data);
+                block.dirty = false;
+            }
+        }
+    }
+
+    /// Write back only `block_nos` if dirty, leaving the rest of the cache
+    /// (and every other inode's buffered writes) untouched. Used by
+    /// `fsync`/`flush`/`release` so syncing one file doesn't force eager
+    /// writeback of unrelated dirty data.
+    pub fn flush_blocks(&self, block_nos: &[u64]) {
+        let mut state = self.state.lock().unwrap();
+        for &block_no in block_nos {
+            if let Some(block) = state.blocks.get_mut(&block_no) {
+                if block.dirty {
+                    self.inner
+                        .write_offset(block_no as usize * BLOCK_SIZE as usize, &block.data);
+                    block.dirty = false;
+                }
+            }
+        }
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for CachedBlockDevice<D> {
+    fn read_offset(&self, offset: usize) -> Vec<u8> {
+        let block_no = (offset / BLOCK_SIZE as usize) as u64;
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(block) = state.blocks.get(&block_no) {
+            let data = block.data.clone();
+            Self::touch(&mut state, block_no);
+            return data;
+        }
+
+        let data = self.inner.read_offset(offset);
+        state.blocks.insert(
+            block_no,
+            CachedBlock {
+                data: data.clone(),
+                dirty: false,
+            },
+        );
+        Self::touch(&mut state, block_no);
+        self.evict_if_needed(&mut state);
+        data
+    }
+
+    fn write_offset(&self, offset: usize, data: &[u8]) {
+        let block_no = (offset / BLOCK_SIZE as usize) as u64;
+        let mut state = self.state.lock().unwrap();
+
+        state.blocks.insert(
+            block_no,
+            CachedBlock {
+                data: data.to_vec(),
+                dirty: true,
+            },
+        );
+        Self::touch(&mut state, block_no);
+        self.evict_if_needed(&mut state);
+    }
+}
@@ -0,0 +1,62 @@
+//! Open file-handle table, following the levitating-fuser example of packing
+//! the granted read/write permission into the top bits of the `fh` the
+//! kernel gets back from `open`/`create`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const FH_READ_BIT: u64 = 1 << 63;
+const FH_WRITE_BIT: u64 = 1 << 62;
+const FH_ID_MASK: u64 = FH_WRITE_BIT - 1;
+
+/// What an open `fh` refers to: which inode, and the access mode it was
+/// opened with.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenHandle {
+    pub inode: u32,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// Maps the opaque `fh` values handed out by `open`/`create` back to the
+/// inode and access mode they were opened with, so `read`/`write` can
+/// validate the handle instead of trusting `ino` blindly.
+pub struct HandleTable {
+    next_id: AtomicU64,
+    handles: HashMap<u64, OpenHandle>,
+}
+
+impl HandleTable {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            handles: HashMap::new(),
+        }
+    }
+
+    /// Allocate a handle for `inode` and return the `fh` to hand back to the
+    /// kernel, with the granted read/write bits packed into its top bits.
+    pub fn open(&mut self, inode: u32, read: bool, write: bool) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) & FH_ID_MASK;
+        self.handles.insert(id, OpenHandle { inode, read, write });
+
+        let mut fh = id;
+        if read {
+            fh |= FH_READ_BIT;
+        }
+        if write {
+            fh |= FH_WRITE_BIT;
+        }
+        fh
+    }
+
+    pub fn get(&self, fh: u64) -> Option<&OpenHandle> {
+        self.handles.get(&(fh & FH_ID_MASK))
+    }
+
+    /// Drop the handle on `release`, returning what it pointed at so the
+    /// caller can flush it.
+    pub fn close(&mut self, fh: u64) -> Option<OpenHandle> {
+        self.handles.remove(&(fh & FH_ID_MASK))
+    }
+}
@@ -4,14 +4,25 @@ extern crate alloc;
 pub use alloc::sync::Arc;
 use clap::{crate_version, Arg, ArgAction, Command};
 use fuser::{
-    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
-    ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+    consts::FOPEN_DIRECT_IO, FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate,
+    ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyLseek, ReplyOpen, ReplyStatfs,
+    ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
 use log::{Level, LevelFilter, Metadata, Record};
 
 use std::ffi::OsStr;
+use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+mod cached_block;
+use cached_block::{CachedBlockDevice, DEFAULT_CACHE_CAPACITY};
+
+mod permissions;
+use permissions::{check_access, clear_suid_sgid};
+
+mod handle;
+use handle::HandleTable;
+
 macro_rules! with_color {
     ($color_code:expr, $($arg:tt)*) => {{
         format_args!("\u{1B}[{}m{}\u{1B}[m", $color_code as u8, format_args!($($arg)*))
@@ -91,6 +102,10 @@ pub const EPIPE: i32 = 32;
 pub const EDOM: i32 = 33;
 pub const ERANGE: i32 = 34;
 pub const EWOULDBLOCK: i32 = EAGAIN;
+pub const ENOTEMPTY: i32 = 39;
+pub const ENODATA: i32 = 61;
+pub const XATTR_CREATE: i32 = 1;
+pub const XATTR_REPLACE: i32 = 2;
 
 pub const S_IFIFO: u32 = 4096;
 pub const S_IFCHR: u32 = 8192;
@@ -112,6 +127,8 @@ pub const S_IRWXO: u32 = 7;
 pub const S_IXOTH: u32 = 1;
 pub const S_IWOTH: u32 = 2;
 pub const S_IROTH: u32 = 4;
+pub const S_ISUID: u32 = 2048;
+pub const S_ISGID: u32 = 1024;
 pub const F_OK: i32 = 0;
 pub const R_OK: i32 = 4;
 pub const W_OK: i32 = 2;
@@ -169,16 +186,22 @@ impl BlockDevice for Disk {
 
 struct Ext4Fuse {
     ext4: Arc<Ext4>,
+    cache: Arc<CachedBlockDevice<Disk>>,
+    handles: HandleTable,
 }
 
 impl Ext4Fuse {
-    pub fn new(ext4: Arc<Ext4>) -> Self {
-        Self { ext4: ext4 }
+    pub fn new(ext4: Arc<Ext4>, cache: Arc<CachedBlockDevice<Disk>>) -> Self {
+        Self {
+            ext4,
+            cache,
+            handles: HandleTable::new(),
+        }
     }
 }
 
 impl Filesystem for Ext4Fuse {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
 
         let mut parent = parent;
         // fuse use 1 as root inode
@@ -187,10 +210,19 @@ impl Filesystem for Ext4Fuse {
             parent = 2;
         }
 
+        let parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, X_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
         let mut path = String::new();
         path += name.to_str().unwrap();
 
-        
+
         let mut file = Ext4File::new();
         let result = self.ext4.ext4_open_from(parent as u32,&mut file, path.as_str(), "r", false);
         // let result = self.ext4.ext4_open_new(&mut file, path.as_str(), "r", false);
@@ -220,16 +252,15 @@ impl Filesystem for Ext4Fuse {
                     ino: file.inode as u64,
                     size: file.fsize,
                     blocks: file.fsize / BLOCK_SIZE as u64,
-                    atime: UNIX_EPOCH,
-                    mtime: UNIX_EPOCH,
-                    ctime: UNIX_EPOCH,
-                    crtime: UNIX_EPOCH,
-                    // fix me
+                    atime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_atime()),
+                    mtime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_mtime()),
+                    ctime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_ctime()),
+                    crtime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_crtime()),
                     kind: file_type,
-                    perm: 0o644,
-                    nlink: 1,
-                    uid: 501,
-                    gid: 20,
+                    perm: inode_ref.inner.inode.ext4_get_inode_mode() & 0o777,
+                    nlink: inode_ref.inner.inode.ext4_inode_get_links_cnt() as u32,
+                    uid: inode_ref.inner.inode.uid as u32,
+                    gid: inode_ref.inner.inode.gid as u32,
                     rdev: 0,
                     flags: 0,
                     blksize: BLOCK_SIZE as u32,
@@ -264,15 +295,15 @@ impl Filesystem for Ext4Fuse {
             ino: inode,
             size: inode_ref.inner.inode.inode_get_size() as u64,
             blocks: inode_ref.inner.inode.inode_get_size() / BLOCK_SIZE as u64,
-            atime: UNIX_EPOCH, // Example static time, adjust accordingly
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
-            kind: file_type, // Adjust according to inode type
-            perm: 0o777,     // Need a method to translate inode perms to Unix perms
+            atime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_atime()),
+            mtime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_mtime()),
+            ctime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_ctime()),
+            crtime: timestamp_to_system_time(inode_ref.inner.inode.ext4_inode_get_crtime()),
+            kind: file_type,
+            perm: inode_ref.inner.inode.ext4_get_inode_mode() & 0o777,
             nlink: link_cnt,
-            uid: 501,
-            gid: 20,
+            uid: inode_ref.inner.inode.uid as u32,
+            gid: inode_ref.inner.inode.gid as u32,
             rdev: 0, // Device nodes not covered here
             flags: 0,
             blksize: BLOCK_SIZE as u32,
@@ -298,6 +329,49 @@ impl Filesystem for Ext4Fuse {
         flags: Option<u32>,
         reply: ReplyAttr,
     ) {
+        let owner_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        let file_uid = owner_ref.inner.inode.uid as u32;
+        let file_gid = owner_ref.inner.inode.gid as u32;
+        let current_mode = owner_ref.inner.inode.ext4_get_inode_mode() & 0o7777;
+
+        // Only the owner (or root) may chmod/chown; everyone else is denied
+        // outright rather than silently downgraded.
+        if (mode.is_some() || uid.is_some() || gid.is_some())
+            && req.uid() != 0
+            && req.uid() != file_uid
+        {
+            reply.error(EACCES);
+            return;
+        }
+
+        // Any other attribute change (truncate via `size`, `utimes`, etc.)
+        // still needs ordinary write access to the file.
+        let changes_other_attrs = size.is_some()
+            || atime.is_some()
+            || mtime.is_some()
+            || ctime.is_some()
+            || crtime.is_some()
+            || chgtime.is_some()
+            || bkuptime.is_some()
+            || flags.is_some();
+        if changes_other_attrs
+            && !check_access(req.uid(), req.gid(), file_uid, file_gid, current_mode as u16, W_OK)
+        {
+            reply.error(EACCES);
+            return;
+        }
+
+        // chown clears S_ISUID/S_ISGID, same as the kernel; an explicit
+        // chmod is left untouched since the caller is setting the mode bits
+        // (including suid/sgid) on purpose.
+        let mode = match mode {
+            Some(m) => Some(m),
+            None if uid.is_some() || gid.is_some() => {
+                Some(clear_suid_sgid(current_mode as u16) as u32)
+            }
+            None => None,
+        };
+
         let attrs = InodeAttr {
             mode,
             uid,
@@ -348,9 +422,9 @@ impl Filesystem for Ext4Fuse {
 
     fn read(
         &mut self,
-        _req: &Request,
+        req: &Request,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
         size: u32,
         _flags: i32,
@@ -358,6 +432,27 @@ impl Filesystem for Ext4Fuse {
         reply: ReplyData,
     ) {
         log::info!("-----------read-----------");
+        match self.handles.get(fh) {
+            None => {
+                reply.error(EBADF);
+                return;
+            }
+            Some(handle) if !handle.read => {
+                reply.error(EACCES);
+                return;
+            }
+            _ => {}
+        }
+
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), ino as u32);
+        let mode = inode_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let file_uid = inode_ref.inner.inode.uid as u32;
+        let file_gid = inode_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), file_uid, file_gid, mode as u16, R_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
         let mut file = Ext4File::new();
         file.inode = ino as u32;
         file.fpos = offset as usize;
@@ -404,7 +499,7 @@ impl Filesystem for Ext4Fuse {
 
     fn write(
         &mut self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: u64,
         fh: u64,
         offset: i64,
@@ -414,6 +509,34 @@ impl Filesystem for Ext4Fuse {
         lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
+        match self.handles.get(fh) {
+            None => {
+                reply.error(EBADF);
+                return;
+            }
+            Some(handle) if !handle.write => {
+                reply.error(EACCES);
+                return;
+            }
+            _ => {}
+        }
+
+        let mut inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), ino as u32);
+        let mode = inode_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let file_uid = inode_ref.inner.inode.uid as u32;
+        let file_gid = inode_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), file_uid, file_gid, mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let full_mode = inode_ref.inner.inode.ext4_get_inode_mode();
+        let cleared_mode = clear_suid_sgid(full_mode);
+        if cleared_mode != full_mode {
+            inode_ref.inner.inode.ext4_inode_set_mode(cleared_mode);
+            inode_ref.write_back_inode();
+        }
+
         let mut file = Ext4File::new();
         file.inode = ino as u32;
         file.fpos = offset as usize;
@@ -423,9 +546,18 @@ impl Filesystem for Ext4Fuse {
     }
 
     /// Remove a file.
-    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
         let path = name.to_str().unwrap_or_default();
         let mut parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent as u32);
+
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
         let mut child = Ext4File::new();
         let open_result = self.ext4.ext4_open(&mut child, path, "r", false);
 
@@ -444,6 +576,505 @@ impl Filesystem for Ext4Fuse {
         }
     }
 
+    /// Create a directory.
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let mut parent_ino = parent;
+        if parent_ino == 1 {
+            parent_ino = 2;
+        }
+
+        let parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let path = match name.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let actual_mode = (mode & !umask) as u16 & 0o777;
+        // `ext4_dir_mk` lays down the new inode plus its `.`/`..` entries,
+        // writing its own updated copy of the parent inode (new dir entry
+        // ⇒ parent size/block pointers change) back to disk as part of
+        // that call. Re-fetch the parent ref afterwards instead of reusing
+        // the pre-call copy above, so bumping `links_cnt` here doesn't
+        // clobber what `ext4_dir_mk` just wrote.
+        match self.ext4.ext4_dir_mk(parent_ino as u32, path, actual_mode) {
+            Ok(inode_num) => {
+                let mut parent_ref =
+                    Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+                let links = parent_ref.inner.inode.ext4_inode_get_links_cnt() + 1;
+                parent_ref.inner.inode.ext4_inode_set_links_cnt(links);
+                parent_ref.write_back_inode();
+
+                let mut inode_ref =
+                    Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode_num as u32);
+                inode_ref.inner.inode.ext4_inode_set_uid(req.uid() as u16);
+                inode_ref.inner.inode.ext4_inode_set_gid(req.gid() as u16);
+                inode_ref.write_back_inode();
+
+                let attr = FileAttr {
+                    ino: inode_num as u64,
+                    size: 0,
+                    blocks: 0,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::Directory,
+                    perm: actual_mode,
+                    nlink: 2,
+                    uid: req.uid(),
+                    gid: req.gid(),
+                    rdev: 0,
+                    flags: 0,
+                    blksize: BLOCK_SIZE as u32,
+                };
+                reply.entry(&TTL, &attr, 0);
+            }
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Remove a directory.
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let mut parent_ino = parent;
+        if parent_ino == 1 {
+            parent_ino = 2;
+        }
+
+        let mut parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let path = match name.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let mut child = Ext4File::new();
+        if self
+            .ext4
+            .ext4_open_from(parent_ino as u32, &mut child, path, "r", false)
+            .is_err()
+        {
+            reply.error(ENOENT);
+            return;
+        }
+
+        let child_mode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), child.inode);
+        let child_mode = child_mode_ref.inner.inode.ext4_get_inode_mode();
+        let child_type = InodeMode::from_bits(child_mode & EXT4_INODE_MODE_TYPE_MASK as u16).unwrap();
+        if child_type != InodeMode::S_IFDIR {
+            reply.error(ENOTDIR);
+            return;
+        }
+
+        let non_dot_entries = self
+            .ext4
+            .read_dir_entry(child.inode as u64)
+            .iter()
+            .filter(|entry| {
+                let name = get_name(entry.name, entry.name_len as usize).unwrap_or_default();
+                name != "." && name != ".."
+            })
+            .count();
+        if non_dot_entries > 0 {
+            reply.error(ENOTEMPTY);
+            return;
+        }
+
+        let mut child_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), child.inode);
+        let result =
+            self.ext4
+                .ext4_dir_remove(&mut parent_ref, &mut child_ref, path, path.len() as u32);
+        match result {
+            EOK => reply.ok(),
+            _ => reply.error(EIO),
+        }
+    }
+
+    /// Rename/move a file, supporting RENAME_NOREPLACE and RENAME_EXCHANGE.
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        const RENAME_NOREPLACE: u32 = 1;
+        const RENAME_EXCHANGE: u32 = 2;
+
+        let mut parent_ino = parent;
+        if parent_ino == 1 {
+            parent_ino = 2;
+        }
+        let mut newparent_ino = newparent;
+        if newparent_ino == 1 {
+            newparent_ino = 2;
+        }
+
+        // Moving an entry needs write access to both the directory losing
+        // it and the directory gaining it.
+        let parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let newparent_ref =
+            Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), newparent_ino as u32);
+        let newparent_mode = newparent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let newparent_uid = newparent_ref.inner.inode.uid as u32;
+        let newparent_gid = newparent_ref.inner.inode.gid as u32;
+        if !check_access(
+            req.uid(),
+            req.gid(),
+            newparent_uid,
+            newparent_gid,
+            newparent_mode as u16,
+            W_OK,
+        ) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let old_path = match name.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+        let new_path = match newname.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let mut existing = Ext4File::new();
+        let dest_exists = self
+            .ext4
+            .ext4_open_from(newparent_ino as u32, &mut existing, new_path, "r", false)
+            .is_ok();
+
+        if flags & RENAME_NOREPLACE != 0 && dest_exists {
+            reply.error(EEXIST);
+            return;
+        }
+        if flags & RENAME_EXCHANGE != 0 && !dest_exists {
+            reply.error(ENOENT);
+            return;
+        }
+
+        let result = if flags & RENAME_EXCHANGE != 0 {
+            self.ext4
+                .ext4_frename_exchange(parent_ino as u32, old_path, newparent_ino as u32, new_path)
+        } else {
+            self.ext4
+                .ext4_frename(parent_ino as u32, old_path, newparent_ino as u32, new_path)
+        };
+
+        match result {
+            Ok(_) => reply.ok(),
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Create a symbolic link, storing the target inline for short paths
+    /// and in a data block otherwise, same as ext4 does for regular files.
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        let mut parent_ino = parent;
+        if parent_ino == 1 {
+            parent_ino = 2;
+        }
+
+        let parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let path = match link_name.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+        let target = match target.to_str() {
+            Some(t) => t,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        match self.ext4.ext4_fsymlink(parent_ino as u32, path, target) {
+            Ok(inode_num) => {
+                let attr = FileAttr {
+                    ino: inode_num as u64,
+                    size: target.len() as u64,
+                    blocks: 0,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::Symlink,
+                    perm: 0o777,
+                    nlink: 1,
+                    uid: req.uid(),
+                    gid: req.gid(),
+                    rdev: 0,
+                    flags: 0,
+                    blksize: BLOCK_SIZE as u32,
+                };
+                reply.entry(&TTL, &attr, 0);
+            }
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Read the target of a symbolic link.
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.ext4.ext4_readlink(ino as u32) {
+            Ok(target) => reply.data(target.as_bytes()),
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Create a hard link.
+    fn link(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let mut newparent_ino = newparent;
+        if newparent_ino == 1 {
+            newparent_ino = 2;
+        }
+
+        let path = match newname.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let mut parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), newparent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let mut child_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), ino as u32);
+
+        let result = self
+            .ext4
+            .ext4_link(&mut parent_ref, &mut child_ref, path, path.len() as u32);
+        match result {
+            EOK => {
+                let links = child_ref.inner.inode.ext4_inode_get_links_cnt() + 1;
+                child_ref.inner.inode.ext4_inode_set_links_cnt(links);
+                child_ref.write_back_inode();
+
+                let mode = child_ref.inner.inode.mode;
+                let inode_type = InodeMode::from_bits(mode & EXT4_INODE_MODE_TYPE_MASK as u16).unwrap();
+                let file_type = match inode_type {
+                    InodeMode::S_IFDIR => FileType::Directory,
+                    InodeMode::S_IFREG => FileType::RegularFile,
+                    _ => FileType::RegularFile,
+                };
+
+                let attr = FileAttr {
+                    ino,
+                    size: child_ref.inner.inode.inode_get_size(),
+                    blocks: child_ref.inner.inode.inode_get_size() / BLOCK_SIZE as u64,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: file_type,
+                    perm: child_ref.inner.inode.ext4_get_inode_mode() & 0o777,
+                    nlink: links as u32,
+                    uid: child_ref.inner.inode.uid as u32,
+                    gid: child_ref.inner.inode.gid as u32,
+                    rdev: 0,
+                    flags: 0,
+                    blksize: BLOCK_SIZE as u32,
+                };
+                reply.entry(&TTL, &attr, 0);
+            }
+            _ => reply.error(EIO),
+        }
+    }
+
+    /// Read an extended attribute, honoring the FUSE size-probe protocol:
+    /// `size == 0` asks for the value's length, a nonzero `size` asks for
+    /// the data itself (or ERANGE if the buffer is too small).
+    fn getxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        match self.ext4.ext4_get_xattr(inode as u32, name) {
+            Ok(value) => {
+                if size == 0 {
+                    reply.size(value.len() as u32);
+                } else if value.len() as u32 > size {
+                    reply.error(ERANGE);
+                } else {
+                    reply.data(&value);
+                }
+            }
+            Err(_) => reply.error(ENODATA),
+        }
+    }
+
+    /// Set an extended attribute, honoring XATTR_CREATE/XATTR_REPLACE.
+    fn setxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let exists = self.ext4.ext4_get_xattr(inode as u32, name).is_ok();
+        if flags & XATTR_CREATE != 0 && exists {
+            reply.error(EEXIST);
+            return;
+        }
+        if flags & XATTR_REPLACE != 0 && !exists {
+            reply.error(ENODATA);
+            return;
+        }
+
+        match self.ext4.ext4_set_xattr(inode as u32, name, value) {
+            Ok(_) => reply.ok(),
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// List extended attribute names, same size-probe protocol as `getxattr`.
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+
+        match self.ext4.ext4_list_xattr(inode as u32) {
+            Ok(names) => {
+                // `listxattr(2)` wants a flat run of NUL-terminated names.
+                let mut buf = Vec::new();
+                for name in &names {
+                    buf.extend_from_slice(name.as_bytes());
+                    buf.push(0);
+                }
+
+                if size == 0 {
+                    reply.size(buf.len() as u32);
+                } else if buf.len() as u32 > size {
+                    reply.error(ERANGE);
+                } else {
+                    reply.data(&buf);
+                }
+            }
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Remove an extended attribute.
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        match self.ext4.ext4_remove_xattr(inode as u32, name) {
+            Ok(_) => reply.ok(),
+            Err(_) => reply.error(ENODATA),
+        }
+    }
+
     /// Create file node.
     /// Create a regular file, character device, block device, fifo or socket node.
     fn mknod(
@@ -505,6 +1136,333 @@ impl Filesystem for Ext4Fuse {
             }
         }
     }
+
+    /// Check whether the calling process can access `ino` the way `mask`
+    /// (R_OK/W_OK/X_OK) describes, for `faccessat`.
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        let mode = inode_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let file_uid = inode_ref.inner.inode.uid as u32;
+        let file_gid = inode_ref.inner.inode.gid as u32;
+
+        if check_access(req.uid(), req.gid(), file_uid, file_gid, mode as u16, mask) {
+            reply.ok();
+        } else {
+            reply.error(EACCES);
+        }
+    }
+
+    /// Open an existing file, granting the access mode decoded from `flags`
+    /// and rejecting it up front with EACCES if the caller isn't entitled
+    /// to it.
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        let mode = inode_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let file_uid = inode_ref.inner.inode.uid as u32;
+        let file_gid = inode_ref.inner.inode.gid as u32;
+
+        let (want_read, want_write) = match flags & libc::O_ACCMODE {
+            libc::O_WRONLY => (false, true),
+            libc::O_RDWR => (true, true),
+            _ => (true, false),
+        };
+        let mask = match (want_read, want_write) {
+            (true, true) => R_OK | W_OK,
+            (false, true) => W_OK,
+            _ => R_OK,
+        };
+        if !check_access(req.uid(), req.gid(), file_uid, file_gid, mode as u16, mask) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let fh = self.handles.open(inode as u32, want_read, want_write);
+        let mut open_flags = 0;
+        if flags & libc::O_DIRECT != 0 {
+            open_flags |= FOPEN_DIRECT_IO;
+        }
+        reply.opened(fh, open_flags);
+    }
+
+    /// Atomically create-and-open a regular file.
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let mut parent_ino = parent;
+        if parent_ino == 1 {
+            parent_ino = 2;
+        }
+
+        let parent_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), parent_ino as u32);
+        let parent_mode = parent_ref.inner.inode.ext4_get_inode_mode() & 0o777;
+        let parent_uid = parent_ref.inner.inode.uid as u32;
+        let parent_gid = parent_ref.inner.inode.gid as u32;
+        if !check_access(req.uid(), req.gid(), parent_uid, parent_gid, parent_mode as u16, W_OK) {
+            reply.error(EACCES);
+            return;
+        }
+
+        let path = match name.to_str() {
+            Some(p) => p,
+            None => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        let actual_mode = (mode & !umask) as u16 & 0o777;
+        let mut ext4_file = Ext4File::new();
+        match self
+            .ext4
+            .ext4_open_from(parent_ino as u32, &mut ext4_file, path, "w+", true)
+        {
+            Ok(_) => {
+                let mut inode_ref =
+                    Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), ext4_file.inode);
+                inode_ref.inner.inode.ext4_inode_set_mode(actual_mode);
+                inode_ref.inner.inode.ext4_inode_set_uid(req.uid() as u16);
+                inode_ref.inner.inode.ext4_inode_set_gid(req.gid() as u16);
+                inode_ref.write_back_inode();
+
+                let (want_read, want_write) = match flags & libc::O_ACCMODE {
+                    libc::O_WRONLY => (false, true),
+                    libc::O_RDWR => (true, true),
+                    _ => (true, false),
+                };
+                let fh = self.handles.open(ext4_file.inode, want_read, want_write);
+
+                let attr = FileAttr {
+                    ino: ext4_file.inode as u64,
+                    size: 0,
+                    blocks: 0,
+                    atime: UNIX_EPOCH,
+                    mtime: UNIX_EPOCH,
+                    ctime: UNIX_EPOCH,
+                    crtime: UNIX_EPOCH,
+                    kind: FileType::RegularFile,
+                    perm: actual_mode,
+                    nlink: 1,
+                    uid: req.uid(),
+                    gid: req.gid(),
+                    rdev: 0,
+                    flags: 0,
+                    blksize: BLOCK_SIZE as u32,
+                };
+                reply.created(&TTL, &attr, 0, fh, 0);
+            }
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Close a handle opened by `open`/`create`, flushing its inode and any
+    /// dirty blocks back through the block device.
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.handles.close(fh);
+
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        inode_ref.write_back_inode();
+        self.cache.flush_blocks(&self.inode_block_numbers(inode as u32));
+        reply.ok();
+    }
+
+    /// POSIX `close()` hook: push the inode and any dirty blocks out without
+    /// closing the handle (it may still be `dup`'d elsewhere).
+    fn flush(&mut self, _req: &Request<'_>, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        if self.handles.get(fh).is_none() {
+            reply.error(EBADF);
+            return;
+        }
+
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        inode_ref.write_back_inode();
+        self.cache.flush_blocks(&self.inode_block_numbers(inode as u32));
+        reply.ok();
+    }
+
+    /// `fsync`/`fdatasync`: same as `flush`, just triggered explicitly.
+    fn fsync(&mut self, _req: &Request<'_>, ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
+        if self.handles.get(fh).is_none() {
+            reply.error(EBADF);
+            return;
+        }
+
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        inode_ref.write_back_inode();
+        self.cache.flush_blocks(&self.inode_block_numbers(inode as u32));
+        reply.ok();
+    }
+
+    /// Report real superblock usage so `df` and friends don't get ENOSYS.
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let sb = &self.ext4.super_block;
+        let total_blocks = sb.blocks_count();
+        let total_inodes = sb.inodes_count() as u64;
+
+        // Free counts come from summing every block group's own counters
+        // (the same fields the allocator updates on every alloc/free)
+        // rather than a single cached value, so `df` tracks reality.
+        let (free_blocks, free_inodes) = self.ext4.block_groups.iter().fold(
+            (0u64, 0u64),
+            |(blocks, inodes), group| {
+                (
+                    blocks + group.get_free_blocks_count(),
+                    inodes + group.get_free_inodes_count() as u64,
+                )
+            },
+        );
+
+        reply.statfs(
+            total_blocks,
+            free_blocks,
+            free_blocks,
+            total_inodes,
+            free_inodes,
+            BLOCK_SIZE as u32,
+            255,
+            BLOCK_SIZE as u32,
+        );
+    }
+
+    /// SEEK_DATA/SEEK_HOLE: walk the inode's extent tree to find the next
+    /// mapped byte or the next gap after `offset`.
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+        let size = inode_ref.inner.inode.inode_get_size();
+
+        if offset < 0 || offset as u64 > size {
+            reply.error(ENXIO);
+            return;
+        }
+
+        let result = match whence {
+            libc::SEEK_DATA => self.ext4.ext4_extent_next_data(inode as u32, offset as u64),
+            libc::SEEK_HOLE => self.ext4.ext4_extent_next_hole(inode as u32, offset as u64),
+            _ => {
+                reply.error(EINVAL);
+                return;
+            }
+        };
+
+        match result {
+            // Already sitting on data/a hole: report the offset unchanged.
+            Ok(Some(found)) if found == offset as u64 => reply.offset(offset),
+            Ok(Some(found)) => reply.offset(found as i64),
+            Ok(None) if whence == libc::SEEK_HOLE => reply.offset(size as i64),
+            Ok(None) => reply.error(ENXIO),
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    /// Preallocate or punch a hole in the extent tree covering
+    /// `[offset, offset + length)`.
+    fn fallocate(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        if offset < 0 || length <= 0 {
+            reply.error(EINVAL);
+            return;
+        }
+
+        let inode = match ino {
+            1 => 2,
+            _ => ino,
+        };
+        let mut inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode as u32);
+
+        let keep_size = mode & libc::FALLOC_FL_KEEP_SIZE != 0;
+        let punch_hole = mode & libc::FALLOC_FL_PUNCH_HOLE != 0;
+
+        let result = if punch_hole {
+            self.ext4
+                .ext4_extent_punch_hole(inode as u32, offset as u64, length as u64)
+        } else {
+            self.ext4
+                .ext4_extent_allocate(inode as u32, offset as u64, length as u64)
+        };
+
+        if result.is_err() {
+            reply.error(EIO);
+            return;
+        }
+
+        // PUNCH_HOLE never grows a file; plain preallocation only grows it
+        // when the caller didn't ask to KEEP_SIZE.
+        if !punch_hole && !keep_size {
+            let end = offset as u64 + length as u64;
+            if end > inode_ref.inner.inode.inode_get_size() {
+                inode_ref.inner.inode.ext4_inode_set_size(end);
+            }
+        }
+
+        let now = system_time_to_secs(SystemTime::now());
+        inode_ref.inner.inode.ext4_inode_set_mtime(now);
+        inode_ref.inner.inode.ext4_inode_set_ctime(now);
+        inode_ref.write_back_inode();
+
+        reply.ok();
+    }
+
+    /// Flush every dirty cached block back to disk on unmount so nothing
+    /// buffered in `cache` is lost.
+    fn destroy(&mut self) {
+        self.cache.flush();
+    }
 }
 
 fn time_now() -> (i64, u32) {
@@ -560,6 +1518,19 @@ pub struct InodeAttr {
     flags: Option<u32>,
 }
 impl Ext4Fuse {
+    /// Physical block numbers currently backing `inode`'s data, so
+    /// `release`/`flush`/`fsync` can write back just this file's dirty
+    /// blocks instead of flushing the whole cache.
+    fn inode_block_numbers(&self, inode: u32) -> Vec<u64> {
+        let inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode);
+        let size = inode_ref.inner.inode.inode_get_size();
+        let block_count = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
+
+        (0..block_count)
+            .filter_map(|lblock| self.ext4.ext4_extent_get_pblock(inode, lblock))
+            .collect()
+    }
+
     pub fn set_attr(&self, inode: u32, attr: &InodeAttr) {
         let mut inode_ref = Ext4InodeRef::get_inode_ref(self.ext4.self_ref.clone(), inode);
 
@@ -611,9 +1582,24 @@ fn main() {
     log::set_logger(&SimpleLogger).unwrap();
     log::set_max_level(LevelFilter::Info);
 
-    let disk = Arc::new(Disk {});
-    let ext4 = Ext4::open(disk);
-    let ext4_fuse = Ext4Fuse::new(ext4);
+    let matches = Command::new("ext4libtest")
+        .version(crate_version!())
+        .arg(
+            Arg::new("cache-blocks")
+                .long("cache-blocks")
+                .help("Number of blocks kept in the write-back block cache")
+                .action(ArgAction::Set),
+        )
+        .get_matches();
+
+    let cache_capacity = matches
+        .get_one::<String>("cache-blocks")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_CACHE_CAPACITY);
+
+    let disk = Arc::new(CachedBlockDevice::new(Disk {}, cache_capacity));
+    let ext4 = Ext4::open(disk.clone());
+    let ext4_fuse = Ext4Fuse::new(ext4, disk);
     let mountpoint = "/root/sync/ext4libtest/foo/";
     let mut options = vec![
         MountOption::RW,
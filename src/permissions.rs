@@ -0,0 +1,67 @@
+//! POSIX permission checks, ported from ayafs's `utils::permissions`.
+
+use crate::{F_OK, R_OK, S_ISGID, S_ISUID, S_IXGRP, S_IXOTH, S_IXUSR, W_OK, X_OK};
+
+/// Evaluate the owner/group/other rwx bits in `mode` against the caller's
+/// identity, mirroring the kernel's `generic_permission()`.
+///
+/// `mask` is the usual `R_OK`/`W_OK`/`X_OK` combination (or `F_OK` to just
+/// confirm existence, which always passes here since the caller already
+/// resolved the inode). Root bypasses every check except `X_OK`, which still
+/// requires at least one execute bit to be set somewhere in `mode` — even
+/// root can't exec a file nobody marked executable.
+pub fn check_access(uid: u32, gid: u32, file_uid: u32, file_gid: u32, mode: u16, mask: i32) -> bool {
+    if mask == F_OK {
+        return true;
+    }
+
+    if uid == 0 {
+        if mask & X_OK != 0 {
+            return mode & (S_IXUSR | S_IXGRP | S_IXOTH) as u16 != 0;
+        }
+        return true;
+    }
+
+    let mode = mode as i32;
+    let mask = mask & (R_OK | W_OK | X_OK);
+
+    let granted = if uid == file_uid {
+        (mode >> 6) & 0o7
+    } else if gid == file_gid || get_groups(uid).contains(&file_gid) {
+        (mode >> 3) & 0o7
+    } else {
+        mode & 0o7
+    };
+
+    granted & mask == mask
+}
+
+/// Look up the supplementary groups of the user owning `uid`, for the
+/// group-membership check in [`check_access`].
+pub fn get_groups(uid: u32) -> Vec<u32> {
+    unsafe {
+        let pw = libc::getpwuid(uid);
+        if pw.is_null() {
+            return Vec::new();
+        }
+        let name = (*pw).pw_name;
+        let primary_gid = (*pw).pw_gid;
+
+        let mut ngroups: libc::c_int = 32;
+        let mut groups = vec![0 as libc::gid_t; ngroups as usize];
+        if libc::getgrouplist(name, primary_gid, groups.as_mut_ptr(), &mut ngroups) < 0 {
+            groups.resize(ngroups.max(0) as usize, 0);
+            libc::getgrouplist(name, primary_gid, groups.as_mut_ptr(), &mut ngroups);
+        }
+        groups.truncate(ngroups.max(0) as usize);
+
+        groups.into_iter().map(|g| g as u32).collect()
+    }
+}
+
+/// Strip S_ISUID/S_ISGID from `mode`, as the kernel does on any write or
+/// chown to a setuid/setgid file to prevent privilege escalation through a
+/// modified binary.
+pub fn clear_suid_sgid(mode: u16) -> u16 {
+    mode & !(S_ISUID | S_ISGID) as u16
+}